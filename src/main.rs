@@ -1,18 +1,19 @@
+mod output;
+mod process;
+#[cfg(unix)]
+mod pty;
+mod shell;
+mod watch;
+
 use clap::Parser;
 use colored::{ColoredString, Colorize};
 use futures::future::join_all;
-use std::{
-    process::Stdio,
-    sync::{
-        Arc,
-        atomic::{AtomicBool, Ordering},
-    },
-};
-use tokio::{
-    io::{AsyncBufReadExt, AsyncRead, BufReader},
-    process::Command,
-    sync::Notify,
-};
+use std::{process::Stdio, str::FromStr, sync::Arc};
+#[cfg(unix)]
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::watch as watch_chan;
+
+use shell::Shell;
 
 /// Run multiple commands in parallel
 #[derive(Parser, Debug, Clone)]
@@ -28,6 +29,78 @@ struct Args {
     /// Dont include prefix in output
     #[arg(long)]
     no_prefix: bool,
+
+    /// Run commands attached to pipes instead of a PTY. PTY mode is the
+    /// default since it gives programs real terminal behaviour (colors,
+    /// progress bars, interactive prompts); piped mode merges nothing and
+    /// keeps stdout/stderr separate, at the cost of looking like a non-tty
+    /// to the child. PTYs aren't available on Windows, so there this flag
+    /// has no effect: every run is piped.
+    #[arg(long)]
+    piped: bool,
+
+    /// Shell used to run each command: sh/bash/zsh/..., cmd, powershell, or
+    /// `none` to exec the command's first token directly with no shell.
+    /// Defaults to the platform shell (`sh` on Unix, `cmd` on Windows).
+    #[arg(long)]
+    shell: Option<Shell>,
+
+    /// Seconds to wait after sending a graceful termination signal before
+    /// force-killing a command that hasn't exited.
+    #[arg(long, default_value_t = 5)]
+    kill_timeout: u64,
+
+    /// Stop every other command as soon as one exits with a non-zero code.
+    #[arg(long)]
+    kill_others_on_fail: bool,
+
+    /// Which exit condition determines the overall success of the run:
+    /// `first` (the first command must succeed), `all` (every command must
+    /// succeed), or `command-N` (the Nth, 0-indexed, command must succeed).
+    #[arg(long, default_value = "all")]
+    success: SuccessMode,
+
+    /// Restart every command whenever a file under one of these paths
+    /// changes, turning this into a dev-loop runner instead of a one-shot
+    /// parallel executor.
+    #[arg(long)]
+    watch: Vec<String>,
+}
+
+/// Which command(s) decide the process's own exit code.
+#[derive(Debug, Clone)]
+enum SuccessMode {
+    First,
+    All,
+    Command(usize),
+}
+
+impl FromStr for SuccessMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "first" => Ok(SuccessMode::First),
+            "all" => Ok(SuccessMode::All),
+            other => other
+                .strip_prefix("command-")
+                .and_then(|n| n.parse::<usize>().ok())
+                .map(SuccessMode::Command)
+                .ok_or_else(|| {
+                    format!("invalid --success value '{other}', expected 'first', 'all', or 'command-N'")
+                }),
+        }
+    }
+}
+
+impl Args {
+    fn shell(&self) -> Shell {
+        self.shell.clone().unwrap_or_else(Shell::default_for_platform)
+    }
+
+    fn kill_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.kill_timeout)
+    }
 }
 
 #[tokio::main]
@@ -38,90 +111,356 @@ async fn main() {
     let inner = control.clone();
     ctrlc::set_handler(move || inner.stop()).unwrap();
 
+    let _watcher = if args.watch.is_empty() {
+        None
+    } else {
+        Some(watch::spawn(&args.watch, control.clone()).unwrap_or_else(|e| {
+            eprintln!("Failed to watch paths: {}", e);
+            std::process::exit(1);
+        }))
+    };
+
+    let (output_tx, output_rx) = output::channel();
+    let writer_handle = tokio::spawn(output::run(output_rx));
+
+    let args = Arc::new(args);
     let tasks = (0..args.commands.len())
-        .map(|i| Task::new(control.clone(), Arc::new(args.clone()), i))
-        .map(|task| tokio::spawn(async move { task.start().await }))
+        .map(|i| Task::new(control.clone(), args.clone(), i, output_tx.clone()))
+        .map(|mut task| tokio::spawn(async move { task.start().await }))
         .collect::<Vec<_>>();
+    drop(output_tx);
 
-    let results = join_all(tasks).await;
-    for result in results {
-        if let Err(e) = result {
-            eprintln!("Task error: {}", e);
+    let mut reports = Vec::with_capacity(args.commands.len());
+    for result in join_all(tasks).await {
+        match result {
+            Ok(report) => {
+                if report.force_killed {
+                    eprintln!(
+                        "[{}] didn't exit in time and was force-killed",
+                        report.label
+                    );
+                } else if let Some(code) = report.exit_code {
+                    if code != 0 {
+                        eprintln!("[{}] exited with code {}", report.label, code);
+                    }
+                } else {
+                    eprintln!("[{}] was terminated by a signal", report.label);
+                }
+                reports.push(report);
+            }
+            Err(e) => eprintln!("Task error: {}", e),
         }
     }
+    let _ = writer_handle.await;
+
+    if !succeeded(&args.success, &reports) {
+        std::process::exit(1);
+    }
+}
+
+fn succeeded(mode: &SuccessMode, reports: &[TaskReport]) -> bool {
+    let ok = |report: &TaskReport| report.exit_code == Some(0);
+    match mode {
+        SuccessMode::All => reports.iter().all(ok),
+        SuccessMode::First => reports.iter().find(|r| r.index == 0).is_some_and(ok),
+        SuccessMode::Command(n) => reports.iter().find(|r| r.index == *n).is_some_and(ok),
+    }
+}
+
+/// Outcome of a single task's run, reported once it exits or is torn down.
+#[derive(Debug)]
+struct TaskReport {
+    index: usize,
+    label: String,
+    exit_code: Option<i32>,
+    force_killed: bool,
+}
+
+/// Raw result of running a single command, before it's paired with the
+/// task's index/label into a [`TaskReport`].
+struct TaskOutcome {
+    exit_code: Option<i32>,
+    force_killed: bool,
 }
 
 #[derive(Debug, Clone)]
 struct TaskControl {
-    notify: Arc<Notify>,
-    stopped: Arc<AtomicBool>,
+    stopped_tx: watch_chan::Sender<bool>,
+    restart_tx: watch_chan::Sender<()>,
 }
 
 impl TaskControl {
     fn new() -> Self {
+        let (stopped_tx, _) = watch_chan::channel(false);
+        let (restart_tx, _) = watch_chan::channel(());
         Self {
-            notify: Arc::new(Notify::new()),
-            stopped: Arc::new(AtomicBool::new(false)),
+            stopped_tx,
+            restart_tx,
         }
     }
 
     pub fn stop(&self) {
-        self.stopped.store(true, Ordering::Relaxed);
-        self.notify.notify_waiters();
+        let _ = self.stopped_tx.send(true);
     }
 
-    pub async fn is_stopped(&self) -> bool {
-        let _ = self.notify.notified().await;
-        let stopped = self.stopped.load(Ordering::Relaxed);
-        stopped
+    fn stopped(&self) -> bool {
+        *self.stopped_tx.borrow()
+    }
+
+    /// Subscribe to the stop signal. Like [`Self::subscribe_restart`], this
+    /// is sticky: a `stop()` that lands before the receiver is next awaited
+    /// is still observed, rather than being missed the way a bare `Notify`
+    /// waiter can be.
+    pub fn subscribe_stopped(&self) -> watch_chan::Receiver<bool> {
+        self.stopped_tx.subscribe()
+    }
+
+    /// Tell every watching task to restart its command, distinct from a
+    /// full [`Self::stop`].
+    pub fn trigger_restart(&self) {
+        let _ = self.restart_tx.send(());
+    }
+
+    /// Subscribe to restarts. Unlike `Notify`, a `watch` receiver remembers
+    /// whether it has missed a value, so a restart triggered between a
+    /// task's child being spawned and it starting to await `changed()` is
+    /// still observed instead of being silently dropped.
+    pub fn subscribe_restart(&self) -> watch_chan::Receiver<()> {
+        self.restart_tx.subscribe()
     }
 }
 
-#[derive(Debug, Clone)]
+/// Wait for `rx` to report the stop signal, returning immediately if it's
+/// already set rather than only reacting to the next transition.
+async fn wait_for_stop(rx: &mut watch_chan::Receiver<bool>) {
+    if *rx.borrow() {
+        return;
+    }
+    let _ = rx.changed().await;
+}
+
+#[derive(Debug)]
 struct Task {
     control: TaskControl,
     args: Arc<Args>,
     index: usize,
+    output: output::Sender,
+    stopped_rx: watch_chan::Receiver<bool>,
+    restart_rx: watch_chan::Receiver<()>,
 }
 
 impl Task {
-    fn new(control: TaskControl, args: Arc<Args>, index: usize) -> Self {
+    fn new(control: TaskControl, args: Arc<Args>, index: usize, output: output::Sender) -> Self {
+        let stopped_rx = control.subscribe_stopped();
+        let restart_rx = control.subscribe_restart();
         Self {
             control,
             index,
             args,
+            output,
+            stopped_rx,
+            restart_rx,
         }
     }
 
-    async fn start(&self) -> Result<(), std::io::Error> {
-        let (command, prefix) = parse_command(&self.args.commands[self.index]);
+    /// Run this task to completion, always producing a [`TaskReport`] even
+    /// if the command itself never managed to spawn (e.g. an unknown
+    /// `--shell none` binary). A task that fails to start is reported as if
+    /// it had been killed by a signal (`exit_code: None`), so it still
+    /// counts as a failure to [`succeeded`] instead of being dropped and
+    /// silently excluded from the aggregate result.
+    async fn start(&mut self) -> TaskReport {
+        let (command, raw_prefix) = parse_command(&self.args.commands[self.index]);
+        let label = raw_prefix.unwrap_or(command).to_string();
+        // Owned so the loop below can call `&mut self` methods without
+        // keeping a borrow of `self.args` alive across them.
+        let command = command.to_string();
+        let prefix = raw_prefix.map(|prefix| colorize(prefix, self.index).to_string());
 
-        let mut cmd = bash_command(command);
+        let outcome = match self.run(&command, prefix).await {
+            Ok(outcome) => outcome,
+            Err(e) => {
+                eprintln!("[{}] failed to start: {}", label, e);
+                TaskOutcome {
+                    exit_code: None,
+                    force_killed: false,
+                }
+            }
+        };
+
+        if outcome.exit_code != Some(0) && self.args.kill_others_on_fail {
+            self.control.stop();
+        }
+
+        TaskReport {
+            index: self.index,
+            label,
+            exit_code: outcome.exit_code,
+            force_killed: outcome.force_killed,
+        }
+    }
+
+    async fn run(
+        &mut self,
+        command: &str,
+        prefix: Option<String>,
+    ) -> Result<TaskOutcome, std::io::Error> {
+        let outcome = loop {
+            let (reason, outcome) = if self.args.piped {
+                self.start_piped(command, prefix.clone()).await?
+            } else {
+                self.start_pty(command, prefix.clone()).await?
+            };
+
+            match reason {
+                ExitReason::Restarted if !self.control.stopped() => continue,
+                // Under `--watch`, a command exiting on its own (crash, or
+                // just a short-lived script) shouldn't end the task: stay
+                // subscribed to `restart_rx` and relaunch on the next file
+                // change, the same way we would if it were still running.
+                ExitReason::Finished
+                    if !self.args.watch.is_empty()
+                        && !self.control.stopped()
+                        && self.wait_for_restart().await =>
+                {
+                    continue;
+                }
+                _ => {}
+            }
+            break outcome;
+        };
+        Ok(outcome)
+    }
+
+    /// Wait for either a restart trigger or the global stop signal, whichever
+    /// comes first. Returns `true` if it was a restart.
+    async fn wait_for_restart(&mut self) -> bool {
+        let stopped = wait_for_stop(&mut self.stopped_rx);
+        let restarted = self.restart_rx.changed();
+        tokio::select! {
+            _ = stopped => false,
+            _ = restarted => true,
+        }
+    }
+
+    async fn start_piped(
+        &mut self,
+        command: &str,
+        prefix: Option<String>,
+    ) -> Result<(ExitReason, TaskOutcome), std::io::Error> {
+        let mut cmd = self.args.shell().command(command);
+        cmd.stdout(Stdio::piped());
+        cmd.stderr(Stdio::piped());
+        cmd.stdin(Stdio::null());
+        cmd.env("FORCE_COLOR", "1");
+        process::new_group(&mut cmd);
         let mut child = cmd.spawn()?;
 
         let stdout = child.stdout.take().unwrap();
         let stderr = child.stderr.take().unwrap();
-        let prefix = match &prefix {
-            Some(prefix) => Some(colorize(prefix, self.index).to_string()),
-            None => None,
+
+        let stdout_handle = output::forward_lines(stdout, prefix.clone(), false, self.output.clone());
+        let stderr_handle = output::forward_lines(stderr, prefix, true, self.output.clone());
+        let stopped = wait_for_stop(&mut self.stopped_rx);
+        let restarted = self.restart_rx.changed();
+
+        let reason = tokio::select! {
+            _ = child.wait() => ExitReason::Finished,
+            _ = stopped => ExitReason::Stopped,
+            _ = restarted => ExitReason::Restarted,
+            _ = stdout_handle => ExitReason::Finished,
+            _ = stderr_handle => ExitReason::Finished,
         };
 
-        let stdout_handle = command_print(stdout, prefix.clone());
-        let stderr_handle = command_print(stderr, prefix);
-        let stopped = self.control.is_stopped();
+        let force_killed = if matches!(reason, ExitReason::Finished) {
+            false
+        } else {
+            process::graceful_shutdown(&mut child, self.args.kill_timeout()).await?
+        };
+        let exit_code = child.wait().await?.code();
+
+        Ok((
+            reason,
+            TaskOutcome {
+                exit_code,
+                force_killed,
+            },
+        ))
+    }
+
+    #[cfg(unix)]
+    async fn start_pty(
+        &mut self,
+        command: &str,
+        prefix: Option<String>,
+    ) -> Result<(ExitReason, TaskOutcome), std::io::Error> {
+        let cmd = self.args.shell().command(command);
+        let winsize = pty::current_winsize();
+        let (mut child, master) = pty::spawn(cmd, winsize)?;
+        let master_fd = master.as_raw_fd();
+
+        let output_handle = output::forward_lines(master, prefix, false, self.output.clone());
+        let stopped = wait_for_stop(&mut self.stopped_rx);
+        let restarted = self.restart_rx.changed();
+        let winch_handle = forward_winsize(master_fd);
 
-        let should_exit = tokio::select! {
-            _ = child.wait() => false,
-            _ = stopped => true,
-            _ = stdout_handle => false,
-            _ = stderr_handle => false,
+        let reason = tokio::select! {
+            _ = child.wait() => ExitReason::Finished,
+            _ = stopped => ExitReason::Stopped,
+            _ = restarted => ExitReason::Restarted,
+            _ = output_handle => ExitReason::Finished,
+            _ = winch_handle => ExitReason::Finished,
         };
 
-        if should_exit {
-            child.kill().await?;
-        }
+        let force_killed = if matches!(reason, ExitReason::Finished) {
+            false
+        } else {
+            process::graceful_shutdown(&mut child, self.args.kill_timeout()).await?
+        };
+        let exit_code = child.wait().await?.code();
+
+        Ok((
+            reason,
+            TaskOutcome {
+                exit_code,
+                force_killed,
+            },
+        ))
+    }
 
-        Ok(())
+    /// PTYs aren't implemented on Windows yet, so there's nothing to
+    /// allocate one against: fall back to piped mode, the same as if
+    /// `--piped` had been passed explicitly.
+    #[cfg(windows)]
+    async fn start_pty(
+        &mut self,
+        command: &str,
+        prefix: Option<String>,
+    ) -> Result<(ExitReason, TaskOutcome), std::io::Error> {
+        self.start_piped(command, prefix).await
+    }
+}
+
+/// Why a command's run loop ended: naturally, because of a global
+/// [`TaskControl::stop`], or because of a [`TaskControl::trigger_restart`]
+/// (only possible with `--watch`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExitReason {
+    Finished,
+    Stopped,
+    Restarted,
+}
+
+/// Re-forward our terminal's window size to the child whenever we receive
+/// SIGWINCH, so e.g. interactive programs re-layout correctly on resize.
+#[cfg(unix)]
+async fn forward_winsize(master_fd: std::os::fd::RawFd) {
+    let mut winch = match signal(SignalKind::window_change()) {
+        Ok(winch) => winch,
+        Err(_) => return,
+    };
+    while winch.recv().await.is_some() {
+        let _ = pty::resize(master_fd, pty::current_winsize());
     }
 }
 
@@ -138,17 +477,6 @@ fn parse_command<'a>(value: &'a str) -> (&'a str, Option<&'a str>) {
     (value, None)
 }
 
-fn bash_command(c: &str) -> Command {
-    let shell = "bash";
-    let mut cmd = Command::new(shell);
-    cmd.args(&["-c", c]);
-    cmd.stdout(Stdio::piped());
-    cmd.stderr(Stdio::piped());
-    cmd.stdin(Stdio::null());
-    cmd.env("FORCE_COLOR", "1");
-    cmd
-}
-
 fn colorize(str: &str, i: usize) -> ColoredString {
     let i = i % 6;
     match i {
@@ -161,20 +489,65 @@ fn colorize(str: &str, i: usize) -> ColoredString {
     }
 }
 
-async fn command_print<C: AsyncRead>(
-    reader: C,
-    prefix: Option<String>,
-) -> Result<(), std::io::Error> {
-    let mut line = String::new();
-    let mut reader = Box::pin(BufReader::new(reader));
-    while let Ok(n) = reader.read_line(&mut line).await {
-        if n > 0 {
-            match &prefix {
-                Some(prefix) => print!("[{}] {}\r\n", prefix, &line.trim()),
-                None => print!("{}\r\n", &line.trim()),
-            }
-            line.clear();
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(index: usize, exit_code: Option<i32>) -> TaskReport {
+        TaskReport {
+            index,
+            label: format!("task-{index}"),
+            exit_code,
+            force_killed: false,
         }
     }
-    Ok(())
+
+    #[test]
+    fn success_mode_parses_known_values() {
+        assert!(matches!(SuccessMode::from_str("first"), Ok(SuccessMode::First)));
+        assert!(matches!(SuccessMode::from_str("all"), Ok(SuccessMode::All)));
+        assert!(matches!(
+            SuccessMode::from_str("command-2"),
+            Ok(SuccessMode::Command(2))
+        ));
+    }
+
+    #[test]
+    fn success_mode_rejects_unknown_values() {
+        assert!(SuccessMode::from_str("command-").is_err());
+        assert!(SuccessMode::from_str("nope").is_err());
+    }
+
+    #[test]
+    fn succeeded_all_requires_every_report_to_exit_zero() {
+        let reports = [report(0, Some(0)), report(1, Some(0))];
+        assert!(succeeded(&SuccessMode::All, &reports));
+
+        let reports = [report(0, Some(0)), report(1, Some(1))];
+        assert!(!succeeded(&SuccessMode::All, &reports));
+    }
+
+    #[test]
+    fn succeeded_all_fails_a_task_that_never_started() {
+        // exit_code: None is what a task that failed to spawn reports; it
+        // must still fail the aggregate, not be treated as a pass.
+        let reports = [report(0, Some(0)), report(1, None)];
+        assert!(!succeeded(&SuccessMode::All, &reports));
+    }
+
+    #[test]
+    fn succeeded_first_only_looks_at_index_zero() {
+        let reports = [report(0, Some(0)), report(1, Some(1))];
+        assert!(succeeded(&SuccessMode::First, &reports));
+
+        let reports = [report(0, Some(1)), report(1, Some(0))];
+        assert!(!succeeded(&SuccessMode::First, &reports));
+    }
+
+    #[test]
+    fn succeeded_command_n_looks_at_that_index() {
+        let reports = [report(0, Some(1)), report(1, Some(0))];
+        assert!(succeeded(&SuccessMode::Command(1), &reports));
+        assert!(!succeeded(&SuccessMode::Command(0), &reports));
+    }
 }
@@ -0,0 +1,72 @@
+use colored::Colorize;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+/// One line of output from a task, destined for the real stdout/stderr.
+#[derive(Debug)]
+pub struct Line {
+    pub prefix: Option<String>,
+    pub is_stderr: bool,
+    pub text: String,
+}
+
+pub type Sender = UnboundedSender<Line>;
+
+/// Create the output channel. Clone the sender once per task; the receiver
+/// is driven by [`run`] until every sender has been dropped.
+pub fn channel() -> (Sender, UnboundedReceiver<Line>) {
+    mpsc::unbounded_channel()
+}
+
+/// Print every line as it arrives. Because there's a single consumer here,
+/// whole prefixed lines are written atomically, even when many tasks (or a
+/// task's own stdout and stderr) produce output concurrently.
+pub async fn run(mut rx: UnboundedReceiver<Line>) {
+    while let Some(line) = rx.recv().await {
+        let text = if line.is_stderr {
+            line.text.dimmed().to_string()
+        } else {
+            line.text
+        };
+        let out = match &line.prefix {
+            Some(prefix) => format!("[{}] {}\r\n", prefix, text),
+            None => format!("{}\r\n", text),
+        };
+        if line.is_stderr {
+            eprint!("{}", out);
+        } else {
+            print!("{}", out);
+        }
+    }
+}
+
+/// Read `reader` line by line and forward each complete line to `tx`,
+/// tagged with `prefix`/`is_stderr`. Only the trailing line terminator is
+/// stripped, so leading whitespace the program intentionally printed is
+/// preserved.
+pub async fn forward_lines<C: AsyncRead>(
+    reader: C,
+    prefix: Option<String>,
+    is_stderr: bool,
+    tx: Sender,
+) {
+    let mut line = String::new();
+    let mut reader = Box::pin(BufReader::new(reader));
+    loop {
+        line.clear();
+        match reader.read_line(&mut line).await {
+            Ok(0) | Err(_) => return,
+            Ok(_) => {
+                let text = line.trim_end_matches(['\n', '\r']).to_string();
+                let sent = tx.send(Line {
+                    prefix: prefix.clone(),
+                    is_stderr,
+                    text,
+                });
+                if sent.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
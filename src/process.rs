@@ -0,0 +1,128 @@
+use std::io;
+use std::time::Duration;
+
+use tokio::process::{Child, Command};
+
+/// Put `cmd`'s child into its own process group (Unix) / process group
+/// (Windows), so that on shutdown we can terminate it and anything it
+/// spawned together, instead of leaking grandchildren.
+#[cfg(unix)]
+pub fn new_group(cmd: &mut Command) {
+    // SAFETY: setsid is async-signal-safe and only called in the child
+    // between fork and exec.
+    unsafe {
+        cmd.pre_exec(|| nix::unistd::setsid().map(|_| ()).map_err(io::Error::from));
+    }
+}
+
+#[cfg(windows)]
+pub fn new_group(cmd: &mut Command) {
+    use std::os::windows::process::CommandExt;
+
+    const CREATE_NEW_PROCESS_GROUP: u32 = 0x0000_0200;
+    cmd.creation_flags(CREATE_NEW_PROCESS_GROUP);
+}
+
+/// Ask the whole process group rooted at `child` to exit, giving it a
+/// chance to flush/clean up before an eventual `kill_group`.
+#[cfg(unix)]
+pub fn terminate_group(child: &Child) -> io::Result<()> {
+    use nix::sys::signal::{kill, Signal};
+    use nix::unistd::Pid;
+
+    let Some(pid) = child.id() else {
+        return Ok(());
+    };
+    kill(Pid::from_raw(-(pid as i32)), Signal::SIGTERM).map_err(io::Error::from)
+}
+
+#[cfg(windows)]
+pub fn terminate_group(child: &Child) -> io::Result<()> {
+    let Some(pid) = child.id() else {
+        return Ok(());
+    };
+    // SAFETY: pid is a live process created with CREATE_NEW_PROCESS_GROUP.
+    let ok = unsafe {
+        windows_sys::Win32::System::Console::GenerateConsoleCtrlEvent(
+            windows_sys::Win32::System::Console::CTRL_BREAK_EVENT,
+            pid,
+        )
+    };
+    if ok == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Send `SIGKILL` to the whole process group rooted at `child` (Unix),
+/// instead of just the immediate child. On Windows only the immediate child
+/// is terminated; processes it spawned are not killed.
+#[cfg(unix)]
+pub fn kill_group(child: &Child) -> io::Result<()> {
+    use nix::sys::signal::{kill, Signal};
+    use nix::unistd::Pid;
+
+    let Some(pid) = child.id() else {
+        return Ok(());
+    };
+    // A negative pid targets the whole process group (see `new_group`, and
+    // `pty::spawn` which puts its child in a new session via `setsid`).
+    kill(Pid::from_raw(-(pid as i32)), Signal::SIGKILL).map_err(io::Error::from)
+}
+
+#[cfg(windows)]
+pub fn kill_group(child: &mut Child) -> io::Result<()> {
+    // `start_kill` is a plain `TerminateProcess` on the child alone, as close
+    // to `SIGKILL` as Windows offers; other members of its process group
+    // are left running.
+    child.start_kill()
+}
+
+/// Send `terminate_group`, then wait up to `timeout` for the child to exit
+/// on its own before escalating to `kill_group`. Returns whether the kill
+/// had to be escalated.
+pub async fn graceful_shutdown(child: &mut Child, timeout: Duration) -> io::Result<bool> {
+    terminate_group(child)?;
+    match tokio::time::timeout(timeout, child.wait()).await {
+        Ok(_) => Ok(false),
+        Err(_) => {
+            kill_group(child)?;
+            let _ = child.wait().await;
+            Ok(true)
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use tokio::process::Command;
+
+    fn spawn(script: &str) -> Child {
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", script]);
+        new_group(&mut cmd);
+        cmd.spawn().unwrap()
+    }
+
+    #[tokio::test]
+    async fn graceful_shutdown_escalates_to_kill_when_term_is_ignored() {
+        let mut child = spawn("trap '' TERM; sleep 5");
+        // Give the shell time to install its trap before we send SIGTERM.
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let force_killed = graceful_shutdown(&mut child, Duration::from_millis(200))
+            .await
+            .unwrap();
+        assert!(force_killed);
+    }
+
+    #[tokio::test]
+    async fn graceful_shutdown_does_not_escalate_when_term_is_honored() {
+        let mut child = spawn("trap 'exit 0' TERM; sleep 5");
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let force_killed = graceful_shutdown(&mut child, Duration::from_secs(2))
+            .await
+            .unwrap();
+        assert!(!force_killed);
+    }
+}
@@ -0,0 +1,137 @@
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use nix::pty::{openpty, Winsize};
+use nix::unistd;
+use tokio::io::{unix::AsyncFd, AsyncRead, AsyncWrite, ReadBuf};
+use tokio::process::{Child, Command};
+
+/// The master side of a PTY, readable/writable from the tokio runtime.
+pub struct PtyMaster(AsyncFd<OwnedFd>);
+
+impl PtyMaster {
+    pub fn as_raw_fd(&self) -> RawFd {
+        self.0.get_ref().as_raw_fd()
+    }
+}
+
+impl AsyncRead for PtyMaster {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        loop {
+            let mut guard = match self.0.poll_read_ready(cx) {
+                Poll::Ready(Ok(guard)) => guard,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            };
+            let unfilled = buf.initialize_unfilled();
+            match guard.try_io(|inner| {
+                unistd::read(inner.get_ref().as_raw_fd(), unfilled).map_err(io::Error::from)
+            }) {
+                Ok(Ok(n)) => {
+                    buf.advance(n);
+                    return Poll::Ready(Ok(()));
+                }
+                Ok(Err(e)) => return Poll::Ready(Err(e)),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+}
+
+impl AsyncWrite for PtyMaster {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        loop {
+            let mut guard = match self.0.poll_write_ready(cx) {
+                Poll::Ready(Ok(guard)) => guard,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            };
+            match guard.try_io(|inner| {
+                unistd::write(inner.get_ref().as_raw_fd(), buf).map_err(io::Error::from)
+            }) {
+                Ok(result) => return Poll::Ready(result),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Spawn `cmd` with its stdin/stdout/stderr attached to a freshly allocated
+/// PTY instead of pipes, returning the child together with the async master
+/// end. A PTY merges stdout and stderr into a single stream.
+pub fn spawn(mut cmd: Command, winsize: Winsize) -> io::Result<(Child, PtyMaster)> {
+    let pty = openpty(Some(&winsize), None)?;
+    let slave_fd = pty.slave.as_raw_fd();
+
+    // SAFETY: only async-signal-safe calls are made between fork and exec.
+    unsafe {
+        cmd.pre_exec(move || {
+            unistd::setsid().map_err(io::Error::from)?;
+            if libc::ioctl(slave_fd, libc::TIOCSCTTY as _, 0) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+
+    let dup_slave = || -> io::Result<std::process::Stdio> {
+        let fd = unistd::dup(slave_fd)?;
+        // SAFETY: fd was just duplicated and is owned by the returned Stdio.
+        Ok(unsafe { std::process::Stdio::from_raw_fd(fd) })
+    };
+    cmd.stdin(dup_slave()?);
+    cmd.stdout(dup_slave()?);
+    cmd.stderr(dup_slave()?);
+
+    let child = cmd.spawn()?;
+    drop(pty.slave);
+
+    let master = AsyncFd::new(pty.master)?;
+    Ok((child, PtyMaster(master)))
+}
+
+/// Forward a window size to a PTY master by raw fd, e.g. in response to a
+/// `SIGWINCH` on our own controlling terminal.
+pub fn resize(fd: RawFd, size: Winsize) -> io::Result<()> {
+    // SAFETY: fd is expected to be an open PTY master for the call's duration.
+    let ret = unsafe { libc::ioctl(fd, libc::TIOCSWINSZ, &size as *const Winsize) };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Read the current window size of our own controlling terminal, falling
+/// back to a sane default when stdout isn't a tty (e.g. output piped to a
+/// file).
+pub fn current_winsize() -> Winsize {
+    let mut size = Winsize {
+        ws_row: 24,
+        ws_col: 80,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    // SAFETY: `size` is a valid out-pointer for TIOCGWINSZ.
+    unsafe {
+        libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size as *mut Winsize);
+    }
+    size
+}
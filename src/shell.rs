@@ -0,0 +1,116 @@
+use std::str::FromStr;
+
+use tokio::process::Command;
+
+/// Which shell (if any) is used to interpret each command string.
+#[derive(Debug, Clone)]
+pub enum Shell {
+    /// A POSIX-ish shell invoked as `<shell> -c <command>`, e.g. `sh`, `bash`, `zsh`.
+    Unix(String),
+    /// `cmd /C <command>`.
+    Cmd,
+    /// `powershell -Command <command>`.
+    Powershell,
+    /// No shell at all: split `<command>` into argv and exec the first token directly.
+    None,
+}
+
+impl Shell {
+    /// The shell used when `--shell` isn't given: `cmd` on Windows, `sh` everywhere else.
+    pub fn default_for_platform() -> Self {
+        if cfg!(windows) {
+            Shell::Cmd
+        } else {
+            Shell::Unix("sh".to_string())
+        }
+    }
+
+    /// Build a `Command` that runs `command` under this shell. Stdio is left
+    /// unconfigured for the caller to set up.
+    pub fn command(&self, command: &str) -> Command {
+        match self {
+            Shell::Unix(shell) => {
+                let mut cmd = Command::new(shell);
+                cmd.args(["-c", command]);
+                cmd
+            }
+            Shell::Cmd => {
+                let mut cmd = Command::new("cmd");
+                cmd.args(["/C", command]);
+                cmd
+            }
+            Shell::Powershell => {
+                let mut cmd = Command::new("powershell");
+                cmd.args(["-Command", command]);
+                cmd
+            }
+            Shell::None => {
+                // Shell-lex rather than `split_whitespace`, so a quoted
+                // argument like `echo "hello world"` stays one argv entry
+                // instead of being split on its inner space and left with
+                // literal quote characters.
+                let mut tokens = shlex::split(command).unwrap_or_default().into_iter();
+                let program = tokens.next().unwrap_or_default();
+                let mut cmd = Command::new(program);
+                cmd.args(tokens);
+                cmd
+            }
+        }
+    }
+}
+
+impl FromStr for Shell {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sh" | "bash" | "zsh" | "dash" | "fish" => Ok(Shell::Unix(s.to_string())),
+            "cmd" => Ok(Shell::Cmd),
+            "powershell" | "pwsh" => Ok(Shell::Powershell),
+            "none" => Ok(Shell::None),
+            other => Err(format!(
+                "unknown shell '{other}', expected one of: sh, bash, zsh, dash, fish, cmd, powershell, none"
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_str_recognizes_unix_shells() {
+        assert!(matches!(Shell::from_str("sh"), Ok(Shell::Unix(s)) if s == "sh"));
+        assert!(matches!(Shell::from_str("bash"), Ok(Shell::Unix(s)) if s == "bash"));
+        assert!(matches!(Shell::from_str("zsh"), Ok(Shell::Unix(s)) if s == "zsh"));
+    }
+
+    #[test]
+    fn from_str_recognizes_cmd_and_powershell() {
+        assert!(matches!(Shell::from_str("cmd"), Ok(Shell::Cmd)));
+        assert!(matches!(Shell::from_str("powershell"), Ok(Shell::Powershell)));
+        assert!(matches!(Shell::from_str("pwsh"), Ok(Shell::Powershell)));
+    }
+
+    #[test]
+    fn from_str_recognizes_none() {
+        assert!(matches!(Shell::from_str("none"), Ok(Shell::None)));
+    }
+
+    #[test]
+    fn from_str_rejects_unknown_shell() {
+        assert!(Shell::from_str("tcsh").is_err());
+    }
+
+    #[test]
+    fn none_shell_lexes_quoted_arguments_instead_of_splitting_on_whitespace() {
+        let cmd = Shell::None.command(r#"echo "hello world""#);
+        let args: Vec<_> = cmd
+            .as_std()
+            .get_args()
+            .map(|a| a.to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(args, vec!["hello world"]);
+    }
+}
@@ -0,0 +1,97 @@
+use std::path::Path;
+use std::time::Duration;
+
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::sync::mpsc;
+
+use crate::TaskControl;
+
+/// Events arriving within this window of each other are coalesced into a
+/// single restart, so e.g. a save-all in an editor doesn't trigger a
+/// restart per file.
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// Watch `paths` for filesystem changes and call [`TaskControl::trigger_restart`]
+/// once a burst of events has settled. The returned watcher must be kept
+/// alive for the duration of the watch; dropping it stops the watch.
+pub fn spawn(paths: &[String], control: TaskControl) -> notify::Result<RecommendedWatcher> {
+    let (tx, rx) = mpsc::unbounded_channel();
+
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+        if res.is_ok() {
+            let _ = tx.send(());
+        }
+    })?;
+
+    for path in paths {
+        watcher.watch(Path::new(path), RecursiveMode::Recursive)?;
+    }
+
+    tokio::spawn(debounce_restarts(rx, control));
+
+    Ok(watcher)
+}
+
+/// Drain `rx` until it closes, triggering one restart per burst of events
+/// that settles for at least [`DEBOUNCE`], rather than one per event.
+async fn debounce_restarts(mut rx: mpsc::UnboundedReceiver<()>, control: TaskControl) {
+    loop {
+        if rx.recv().await.is_none() {
+            return;
+        }
+        // Drain/coalesce further events until the burst settles.
+        loop {
+            match tokio::time::timeout(DEBOUNCE, rx.recv()).await {
+                Ok(Some(())) => continue,
+                Ok(None) => return,
+                Err(_elapsed) => break,
+            }
+        }
+        control.trigger_restart();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn coalesces_a_burst_of_events_into_one_restart() {
+        let control = TaskControl::new();
+        let mut restarted = control.subscribe_restart();
+        let (tx, rx) = mpsc::unbounded_channel();
+        let handle = tokio::spawn(debounce_restarts(rx, control));
+
+        for _ in 0..5 {
+            tx.send(()).unwrap();
+        }
+
+        tokio::time::timeout(DEBOUNCE * 2, restarted.changed())
+            .await
+            .expect("burst should have triggered a restart")
+            .unwrap();
+        assert!(
+            tokio::time::timeout(DEBOUNCE * 2, restarted.changed())
+                .await
+                .is_err(),
+            "burst should have triggered only one restart"
+        );
+
+        drop(tx);
+        handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn returns_once_the_sender_is_dropped() {
+        let control = TaskControl::new();
+        let (tx, rx) = mpsc::unbounded_channel();
+        let handle = tokio::spawn(debounce_restarts(rx, control));
+
+        drop(tx);
+
+        tokio::time::timeout(DEBOUNCE * 2, handle)
+            .await
+            .expect("loop should exit once its sender is dropped")
+            .unwrap();
+    }
+}